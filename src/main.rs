@@ -1,12 +1,45 @@
 //! Blinks the LED on a Pico board
 //!
 //! This will blink an LED attached to GP25, which is the pin the Pico uses for the on-board LED.
+//!
+//! ## Logging
+//!
+//! By default `defmt` is carried over RTT, which needs a probe/debugger attached. Build with
+//! `--features log-uart` instead to route `defmt` over UART0 on GP16 (TX) / GP17 (RX) at
+//! 115200 8N1, so the board can be read from a plain serial terminal when running standalone.
+//! Because `defmt` frames are Rzcobs-encoded binary, not text, the other end must capture the
+//! raw bytes (e.g. `stty -F /dev/ttyUSB0 115200 raw` then pipe `/dev/ttyUSB0` into
+//! `defmt-print -e <elf>`), not view them in a regular terminal emulator.
+//!
+//! ## Dynamic text
+//!
+//! Build with `--features alloc` to install a small heap (see [`HEAP`]) and enable
+//! [`lcd_printf!`], a `format!`-like helper that renders runtime values (counters, sensor
+//! readings, ...) to the LCD without hand-rolling `heapless`/`core::fmt::Write` plumbing.
+//! `no-alloc` builds are unaffected; the menu simply skips the extra counter slot.
+//!
+//! ## Status LED
+//!
+//! The blink loop drives the on-board LED through the [`StatusLed`] trait rather than a raw
+//! `OutputPin`, because on the Pico W the LED hangs off the cyw43 wireless module instead of a
+//! GPIO. Build with `--features pico-w` to select [`NoStatusLed`] (a no-op placeholder; this
+//! example doesn't bring up `cyw43` itself) in place of the plain GPIO implementation — swap in
+//! [`Cyw43StatusLed`] once you have a `cyw43::Control` from your own bring-up. Either way, the
+//! rest of this file is unchanged.
 #![no_std]
 #![no_main]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use core::time::Duration;
 use bsp::entry;
+use bsp::hal::pac::interrupt;
+use critical_section::Mutex;
 use defmt::*;
+#[cfg(not(feature = "log-uart"))]
 use defmt_rtt as _;
 use lcd1602_rs::LCD1602;
 use panic_probe as _;
@@ -18,23 +51,152 @@ use rp_pico as bsp;
 
 use bsp::hal::{
     clocks::{init_clocks_and_plls},
+    gpio::{bank0::Gpio15, FunctionSio, Interrupt::EdgeLow, Pin, PullUp, SioInput},
     pac,
     sio::Sio,
     watchdog::Watchdog,
 };
-use cortex_m::delay::Delay;
-use cortex_m::peripheral::syst::SystClkSource;
+use embedded_hal::digital::v2::{OutputPin, ToggleableOutputPin};
 use embedded_hal::timer::{Cancel, CountDown, Periodic};
 use rp_pico::hal;
 use nb;
-use rp_pico::hal::Clock;
 use void::Void;
 
+#[cfg(feature = "log-uart")]
+use rp_pico::hal::uart::{DataBits, StopBits, UartConfig, UartPeripheral};
+#[cfg(feature = "log-uart")]
+use fugit::RateExtU32;
+#[cfg(feature = "log-uart")]
+use rp_pico::hal::Clock;
+#[cfg(feature = "log-uart")]
+use rp_pico::hal::gpio::{bank0::Gpio16, bank0::Gpio17, FunctionUart, PullDown};
+
+#[cfg(feature = "alloc")]
+use embedded_alloc::Heap;
+
+/// The LCD1602 is 16 columns wide; dynamic strings are truncated to fit a single line.
+const LCD_COLUMNS: usize = 16;
+
+/// The messages the menu button cycles through, in order. With the `alloc` feature enabled,
+/// the button cycles one slot further into a live "presses: N" counter built with
+/// [`lcd_printf!`] instead of a fixed string.
+const MESSAGES: [&str; 3] = ["hello world!", "button pressed", "menu item #3"];
+
+/// Minimum spacing between accepted button edges, to debounce a mechanical switch.
+const DEBOUNCE_US: u64 = 20_000;
+
+/// Baud rate for the `log-uart` defmt transport. Change this (and the receiving end's
+/// settings) together if 115200 8N1 doesn't suit your setup.
+#[cfg(feature = "log-uart")]
+const LOG_UART_BAUD_RATE: u32 = 115_200;
+
+/// Concrete type of the enabled UART0 used to carry `defmt` frames, so it can be named for
+/// [`cortex_m::singleton!`].
+#[cfg(feature = "log-uart")]
+type LogUart = UartPeripheral<
+    hal::uart::Enabled,
+    pac::UART0,
+    (
+        Pin<Gpio16, FunctionUart, PullDown>,
+        Pin<Gpio17, FunctionUart, PullDown>,
+    ),
+>;
+
+type ButtonPin = Pin<Gpio15, FunctionSio<SioInput>, PullUp>;
+
+/// The button's GPIO, parked here so `IO_IRQ_BANK0` can clear its interrupt and read it.
+static BUTTON: Mutex<RefCell<Option<ButtonPin>>> = Mutex::new(RefCell::new(None));
+/// The one `hal::Timer` instance for the whole program. `hal::Timer` isn't `Clone`, so every
+/// consumer that needs to read the tick count — the debounce check, the status LED blink, and
+/// every `Timer` (our `CountDown` wrapper) — goes through [`ticks_now`]/[`ticks_now_in`] instead
+/// of owning a copy of it.
+static TIMER: Mutex<RefCell<Option<hal::Timer>>> = Mutex::new(RefCell::new(None));
+/// Tick (microsecond) count of the last edge that passed the debounce check.
+static LAST_EDGE_US: AtomicU64 = AtomicU64::new(0);
+/// Menu slot selected by the button (an index into `MESSAGES`, or with the `alloc` feature
+/// `MESSAGES.len()` for the counter slot); read by the main loop.
+static MESSAGE_INDEX: AtomicUsize = AtomicUsize::new(0);
+/// Total number of debounced button presses, shown by the `alloc` feature's counter slot.
+#[cfg(feature = "alloc")]
+static PRESS_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Reads the shared [`TIMER`]'s tick count, given a critical-section token already held by the
+/// caller (e.g. from inside an interrupt handler). Reads as `0` before [`TIMER`] is populated.
+fn ticks_now_in(cs: critical_section::CriticalSection) -> u64 {
+    TIMER
+        .borrow(cs)
+        .borrow()
+        .as_ref()
+        .map(|t| t.get_counter().ticks())
+        .unwrap_or(0)
+}
+
+/// Reads the shared [`TIMER`]'s tick count, taking its own critical section.
+fn ticks_now() -> u64 {
+    critical_section::with(ticks_now_in)
+}
+
+/// Number of menu slots, including the `alloc`-only live counter slot.
+const fn menu_len() -> usize {
+    #[cfg(feature = "alloc")]
+    {
+        MESSAGES.len() + 1
+    }
+    #[cfg(not(feature = "alloc"))]
+    {
+        MESSAGES.len()
+    }
+}
+
+/// Global heap used only by [`lcd_printf!`]; sized generously for 16-column strings.
+#[cfg(feature = "alloc")]
+#[global_allocator]
+static HEAP: Heap = Heap::empty();
+
+/// Size of the static arena backing [`HEAP`].
+#[cfg(feature = "alloc")]
+const HEAP_SIZE_BYTES: usize = 1024;
+
+#[cfg(feature = "alloc")]
+static mut HEAP_MEM: [u8; HEAP_SIZE_BYTES] = [0; HEAP_SIZE_BYTES];
+
+/// Truncates `s` to at most [`LCD_COLUMNS`] bytes, cutting on the last `char` boundary at or
+/// before that width. `String::truncate` panics unless the cut lands on a char boundary, which
+/// a plain byte truncate can't guarantee once a multi-byte character (e.g. `°`) is in play.
+#[cfg(feature = "alloc")]
+fn truncate_to_lcd_width(s: &mut alloc::string::String) {
+    if s.len() <= LCD_COLUMNS {
+        return;
+    }
+    let cut = s
+        .char_indices()
+        .map(|(i, _)| i)
+        .take_while(|&i| i <= LCD_COLUMNS)
+        .last()
+        .unwrap_or(0);
+    s.truncate(cut);
+}
+
+/// Formats `$fmt, $args...` into a heap `String`, truncates it to the LCD's column width, and
+/// writes it to `$lcd`. Requires the `alloc` feature and a prior [`HEAP`] initialization.
+#[cfg(feature = "alloc")]
+#[macro_export]
+macro_rules! lcd_printf {
+    ($lcd:expr, $($arg:tt)*) => {{
+        let mut s = alloc::format!($($arg)*);
+        $crate::truncate_to_lcd_width(&mut s);
+        $lcd.print(&s)
+    }};
+}
+
 #[entry]
 fn main() -> ! {
-    info!("Program start");
+    #[cfg(feature = "alloc")]
+    unsafe {
+        HEAP.init(core::ptr::addr_of!(HEAP_MEM) as usize, HEAP_SIZE_BYTES);
+    }
+
     let mut pac = pac::Peripherals::take().unwrap();
-    let core = pac::CorePeripherals::take().unwrap();
     let mut watchdog = Watchdog::new(pac.WATCHDOG);
     let sio = Sio::new(pac.SIO);
 
@@ -52,8 +214,12 @@ fn main() -> ! {
     .ok()
     .unwrap();
 
-    let delay = Delay::new(core.SYST, 133000000);
-    let timer: Timer = Timer::new(delay);
+    let hal_timer = hal::Timer::new(pac.TIMER, &mut pac.RESETS, &clocks);
+    critical_section::with(|cs| {
+        TIMER.borrow(cs).replace(Some(hal_timer));
+    });
+
+    let timer: Timer = Timer::new();
 
     let pins = bsp::Pins::new(
         pac.IO_BANK0,
@@ -62,6 +228,39 @@ fn main() -> ! {
         &mut pac.RESETS,
     );
 
+    // Route defmt over UART0 (GP16 TX / GP17 RX, 115200 8N1) instead of RTT when built with
+    // `--features log-uart`, so the log is readable from a standalone board over a serial cable.
+    #[cfg(feature = "log-uart")]
+    {
+        let uart_pins = (
+            pins.gpio16.into_function::<FunctionUart>(),
+            pins.gpio17.into_function::<FunctionUart>(),
+        );
+        let uart = UartPeripheral::new(pac.UART0, uart_pins, &mut pac.RESETS)
+            .enable(
+                UartConfig::new(LOG_UART_BAUD_RATE.Hz(), DataBits::Eight, None, StopBits::One),
+                clocks.peripheral_clock.freq(),
+            )
+            .unwrap();
+        // `defmt_serial` needs a `'static mut` reference; `main` runs exactly once, so stashing
+        // `uart` in a singleton slot is sound and avoids a `static mut` of our own.
+        let uart: &'static mut LogUart = cortex_m::singleton!(: LogUart = uart).unwrap();
+        defmt_serial::defmt_serial(uart);
+    }
+
+    info!("Program start");
+
+    // GP15 selects the displayed message: pull-up input, interrupt on the falling edge
+    // produced when the button shorts it to ground.
+    let button = pins.gpio15.into_pull_up_input();
+    button.set_interrupt_enabled(EdgeLow, true);
+    critical_section::with(|cs| {
+        BUTTON.borrow(cs).replace(Some(button));
+    });
+    unsafe {
+        pac::NVIC::unmask(pac::Interrupt::IO_IRQ_BANK0);
+    }
+
     // Init pins
     let rs = pins.gpio0.into_push_pull_output();
     let en = pins.gpio1.into_push_pull_output();
@@ -70,41 +269,201 @@ fn main() -> ! {
     let d6 = pins.gpio4.into_push_pull_output();
     let d7 = pins.gpio5.into_push_pull_output();
 
-    // This is the correct pin on the Raspberry Pico board. On other boards, even if they have an
-    // on-board LED, it might need to be changed.
-    //
-    // Notably, on the Pico W, the LED is not connected to any of the RP2040 GPIOs but to the cyw43 module instead.
-    // One way to do that is by using [embassy](https://github.com/embassy-rs/embassy/blob/main/examples/rp/src/bin/wifi_blinky.rs)
-    //
-    // If you have a Pico W and want to toggle a LED with a simple GPIO output pin, you can connect an external
-    // LED to one of the GPIO pins, and reference that pin here. Don't forget adding an appropriate resistor
-    // in series with the LED.
     let mut lcd = LCD1602::new(en, rs, d4, d5, d6, d7, timer).unwrap();
 
+    // `pins.led` (GP25) is the correct pin on a plain Raspberry Pico; on other boards it may
+    // need to change. On the Pico W it isn't wired up at all, so `--features pico-w` swaps in
+    // `NoStatusLed` here — this example doesn't bring up cyw43 (see its module docs), so there's
+    // no `Control` to construct `Cyw43StatusLed` with yet. Swap in a real one once you have it;
+    // the blink loop below needs no changes either way.
+    #[cfg(not(feature = "pico-w"))]
+    let mut status_led = pins.led.into_push_pull_output();
+    #[cfg(feature = "pico-w")]
+    let mut status_led = NoStatusLed;
+
+    let mut blink_timer = Timer::new().periodic(true);
+    blink_timer.start(Duration::from_millis(500));
+
+    let mut shown = usize::MAX;
     loop {
-        lcd.print("hello world!").ok();
-        lcd.delay(1_000_000u64).ok();
-        lcd.clear().ok();
-        lcd.delay(1_000_000u64).ok();
+        if blink_timer.wait().is_ok() {
+            // `status_led` is an RP2040 GPIO `OutputPin` on boards without `pico-w`, which
+            // also has `ToggleableOutputPin::toggle` (that trait is in scope too); disambiguate in
+            // favor of our `StatusLed` so the call works the same under `--features pico-w` too.
+            StatusLed::toggle(&mut status_led);
+        }
+
+        let selected = MESSAGE_INDEX.load(Ordering::Relaxed);
+        if selected != shown {
+            shown = selected;
+            lcd.clear().ok();
+            if shown < MESSAGES.len() {
+                lcd.print(MESSAGES[shown]).ok();
+            } else {
+                #[cfg(feature = "alloc")]
+                {
+                    lcd_printf!(lcd, "presses: {}", PRESS_COUNT.load(Ordering::Relaxed)).ok();
+                }
+            }
+        }
+    }
+}
+
+/// Fires on any enabled GPIO bank0 edge. Advances `MESSAGE_INDEX` on a debounced GP15 press.
+#[interrupt]
+fn IO_IRQ_BANK0() {
+    critical_section::with(|cs| {
+        let mut button = BUTTON.borrow(cs).borrow_mut();
+        let Some(button) = button.as_mut() else {
+            return;
+        };
+        if !button.interrupt_status(EdgeLow) {
+            return;
+        }
+        button.clear_interrupt(EdgeLow);
+
+        let now = ticks_now_in(cs);
+        let last = LAST_EDGE_US.load(Ordering::Relaxed);
+        if now.wrapping_sub(last) < DEBOUNCE_US {
+            return;
+        }
+        LAST_EDGE_US.store(now, Ordering::Relaxed);
+
+        #[cfg(feature = "alloc")]
+        PRESS_COUNT.fetch_add(1, Ordering::Relaxed);
+
+        MESSAGE_INDEX
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |i| {
+                Some((i + 1) % menu_len())
+            })
+            .ok();
+    });
+}
+
+/// Abstracts the on-board status indicator so the same blink loop runs whether the LED is a
+/// plain RP2040 GPIO (most boards) or sits behind the cyw43 wireless module (Pico W).
+pub trait StatusLed {
+    /// Drives the indicator to its "on" state.
+    fn set_high(&mut self);
+    /// Drives the indicator to its "off" state.
+    fn set_low(&mut self);
+    /// Flips the indicator from its current state.
+    fn toggle(&mut self);
+}
+
+impl<P> StatusLed for P
+where
+    P: OutputPin + ToggleableOutputPin,
+{
+    fn set_high(&mut self) {
+        OutputPin::set_high(self).ok();
+    }
+
+    fn set_low(&mut self) {
+        OutputPin::set_low(self).ok();
+    }
+
+    fn toggle(&mut self) {
+        ToggleableOutputPin::toggle(self).ok();
+    }
+}
+
+/// Drives the status LED over a `cyw43::Control`, for boards (Pico W) where it isn't wired to
+/// a GPIO. Compiled only with the `pico-w` feature; `main` does not construct one and uses
+/// [`NoStatusLed`] instead.
+///
+/// Bringing up `cyw43` itself needs PIO0 plus a DMA channel for its SPI link and an async
+/// executor to drive `cyw43::Runner` in the background; that setup lives with whichever
+/// executor the firmware uses (see the `cyw43-pio` crate) and is out of scope for this
+/// blocking example. Once a `Control` is available, handing it to `Cyw43StatusLed::new` is
+/// the only change `main` needs.
+#[cfg(feature = "pico-w")]
+pub struct Cyw43StatusLed<'a> {
+    control: cyw43::Control<'a>,
+    state: bool,
+}
+
+#[cfg(feature = "pico-w")]
+impl<'a> Cyw43StatusLed<'a> {
+    /// Wraps an already-initialized `cyw43::Control`.
+    pub fn new(control: cyw43::Control<'a>) -> Self {
+        Cyw43StatusLed {
+            control,
+            state: false,
+        }
     }
 }
 
-/// A simple Timer struct
+#[cfg(feature = "pico-w")]
+impl<'a> StatusLed for Cyw43StatusLed<'a> {
+    fn set_high(&mut self) {
+        self.state = true;
+        embassy_futures::block_on(self.control.gpio_set(0, true));
+    }
+
+    fn set_low(&mut self) {
+        self.state = false;
+        embassy_futures::block_on(self.control.gpio_set(0, false));
+    }
+
+    fn toggle(&mut self) {
+        if self.state {
+            self.set_low();
+        } else {
+            self.set_high();
+        }
+    }
+}
+
+/// A `StatusLed` that does nothing. This example doesn't bring up `cyw43` (see
+/// [`Cyw43StatusLed`]'s docs for why), so under `--features pico-w` there's no `Control` to
+/// drive the real LED with yet; this placeholder keeps the blink loop compiling and running
+/// without one. Swap in `Cyw43StatusLed` once you have a `Control`.
+#[cfg(feature = "pico-w")]
+pub struct NoStatusLed;
+
+#[cfg(feature = "pico-w")]
+impl StatusLed for NoStatusLed {
+    fn set_high(&mut self) {}
+
+    fn set_low(&mut self) {}
+
+    fn toggle(&mut self) {}
+}
+
+/// A non-blocking `CountDown` backed by the RP2040's 64-bit microsecond hardware timer.
+///
+/// Unlike a `cortex_m::delay::Delay`-based timer, `wait()` never spins: it compares the
+/// current tick count against a target deadline and returns `WouldBlock` until it elapses.
+/// In periodic mode the deadline is advanced by the configured duration rather than reset
+/// from `now`, so successive periods don't accumulate drift. `start` always sets the deadline
+/// from the current tick count.
+///
+/// `hal::Timer` isn't `Clone`, and this program has several consumers (the LCD driver's and the
+/// status LED blink's countdowns, and the button debounce) that all read the same hardware tick
+/// count. So `Timer` doesn't own a `hal::Timer` itself; it reads ticks through [`ticks_now`],
+/// which shares the single instance parked in the [`TIMER`] static.
 pub struct Timer {
-    duration: Duration,
+    duration_us: u64,
+    deadline: Option<u64>,
     periodic: bool,
-    delay: Delay,
 }
 
 impl Timer {
-    /// Creates a new Timer
-    pub fn new(delay: Delay) -> Self {
+    /// Creates a new Timer reading from the shared [`TIMER`].
+    pub fn new() -> Self {
         Timer {
-            duration: Duration::from_secs(0),
+            duration_us: 0,
+            deadline: None,
             periodic: false,
-            delay,
         }
     }
+
+    /// Selects whether this Timer restarts itself after each completed wait.
+    pub fn periodic(mut self, periodic: bool) -> Self {
+        self.periodic = periodic;
+        self
+    }
 }
 
 impl CountDown for Timer {
@@ -114,11 +473,22 @@ impl CountDown for Timer {
     where
         T: Into<Self::Time>,
     {
-        self.duration = count.into();
+        self.duration_us = count.into().as_micros() as u64;
+        self.deadline = Some(ticks_now() + self.duration_us);
     }
 
     fn wait(&mut self) -> nb::Result<(), Void> {
-        self.delay.delay_us(self.duration.as_micros() as u32);
+        let deadline = self.deadline.ok_or(nb::Error::WouldBlock)?;
+        let now = ticks_now();
+        if now < deadline {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        self.deadline = if self.periodic {
+            Some(deadline + self.duration_us)
+        } else {
+            None
+        };
         Ok(())
     }
 }
@@ -129,7 +499,10 @@ impl Cancel for Timer {
     type Error = &'static str;
 
     fn cancel(&mut self) -> Result<(), Self::Error> {
-      Ok(())
+        match self.deadline.take() {
+            Some(_) => Ok(()),
+            None => Err("timer is not running"),
+        }
     }
 }
 